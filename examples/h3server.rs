@@ -37,6 +37,10 @@ const LOCAL_CONN_ID_LEN: usize = 16;
 
 const MAX_DATAGRAM_SIZE: usize = 1452;
 
+// Bound on the number of unreliable DATAGRAM frames queued in either
+// direction per connection.
+const DGRAM_QUEUE_LEN: usize = 1000;
+
 const USAGE: &str = "Usage:
   h3server [options]
   h3server -h | --help
@@ -47,14 +51,17 @@ Options:
   --key <file>      TLS certificate key path [default: examples/cert.key]
   --root <dir>      Root directory [default: examples/root/]
   --name <str>      Name of the server [default: quic.tech]
+  --token-ttl <secs>  Address validation token validity window, in seconds [default: 60]
+  --doh <addr>      Enable DNS-over-HTTP/3 and forward /dns-query requests to this upstream resolver
   -h --help         Show this screen.
 ";
 
-type H3ConnMap = HashMap<Vec<u8>, (net::SocketAddr, Box<quiche::h3::H3Connection>)>;
+type H3ConnMap = HashMap<Vec<u8>, Box<quiche::h3::H3Connection>>;
 
 fn main() {
     let mut buf = [0; 65535];
     let mut out = [0; MAX_DATAGRAM_SIZE];
+    let mut dgram_buf = [0; MAX_DATAGRAM_SIZE];
 
     env_logger::init();
 
@@ -63,6 +70,7 @@ fn main() {
                       .unwrap_or_else(|e| e.exit());
 
     let socket = net::UdpSocket::bind(args.get_str("--listen")).unwrap();
+    let local_addr = socket.local_addr().unwrap();
 
     let poll = mio::Poll::new().unwrap();
     let mut events = mio::Events::with_capacity(1024);
@@ -88,14 +96,26 @@ fn main() {
     config.quiche_config.set_initial_max_stream_data_bidi_remote(1_000_000);
     config.quiche_config.set_initial_max_streams_bidi(100);
     config.quiche_config.set_initial_max_streams_uni(100);
-    config.quiche_config.set_disable_migration(true);
+    config.quiche_config.set_disable_migration(false);
+
+    // Enable the DATAGRAM extension so unreliable DATAGRAM frames (e.g.
+    // fragmented media) bypass stream flow control and retransmission.
+    config.quiche_config.enable_dgram(true, DGRAM_QUEUE_LEN, DGRAM_QUEUE_LEN);
 
     config.set_root_dir(&String::from(args.get_str("--root")));
 
+    if !args.get_str("--doh").is_empty() {
+        let upstream = args.get_str("--doh").parse().unwrap();
+        config.set_doh_upstream(upstream);
+    }
+
+    let token_ttl = args.get_str("--token-ttl").parse().unwrap();
+    let validator = quiche::addr_validation::AddrValidator::new(std::time::Duration::from_secs(token_ttl));
+
     loop {
         // TODO: use event loop that properly supports timers
         let timeout = h3connections.values()
-                                 .filter_map(|(_, c)| c.quic_conn.timeout())
+                                 .filter_map(|c| c.quic_conn.timeout())
                                  .min();
 
         poll.poll(&mut events, timeout).unwrap();
@@ -104,7 +124,7 @@ fn main() {
             if events.is_empty() {
                 debug!("timed out");
 
-                h3connections.values_mut().for_each(|(_, c)| c.quic_conn.on_timeout());
+                h3connections.values_mut().for_each(|c| c.quic_conn.on_timeout());
 
                 break 'read;
             }
@@ -140,7 +160,7 @@ fn main() {
                 continue;
             }
 
-            let (_, h3conn) = if !h3connections.contains_key(&hdr.dcid) {
+            let h3conn = if !h3connections.contains_key(&hdr.dcid) {
                 if hdr.ty != quiche::Type::Initial {
                     error!("Packet is not Initial");
                     continue;
@@ -167,7 +187,7 @@ fn main() {
                 if token.is_empty() {
                     warn!("Doing stateless retry");
 
-                    let new_token = mint_token(&hdr, &src);
+                    let new_token = validator.mint(&hdr.dcid, &src);
 
                     let len = quiche::retry(&hdr.scid, &hdr.dcid, &scid,
                                             &new_token, &mut out).unwrap();
@@ -177,9 +197,9 @@ fn main() {
                     continue;
                 }
 
-                let odcid = validate_token(&src, token);
+                let odcid = validator.validate(&src, token);
 
-                if odcid == None {
+                if odcid.is_none() {
                     error!("Invalid address validation token");
                     continue;
                 }
@@ -189,17 +209,19 @@ fn main() {
                         hex_dump(&hdr.scid),
                         hex_dump(&scid));
 
-                let conn = quiche::h3::accept(&scid, odcid, &mut config).unwrap();
+                let conn = quiche::h3::accept(&scid, odcid.as_deref(), local_addr, src, &hdr.scid, &mut config).unwrap();
 
-                h3connections.insert(scid.to_vec(), (src, conn));
+                h3connections.insert(scid.to_vec(), conn);
 
                 h3connections.get_mut(&scid[..]).unwrap()
             } else {
                 h3connections.get_mut(&hdr.dcid).unwrap()
             };
 
+            let recv_info = quiche::RecvInfo { from: src, to: local_addr };
+
             // Process potentially coalesced packets.
-            let read = match h3conn.quic_conn.recv(buf) {
+            let read = match h3conn.quic_conn.recv(buf, recv_info) {
                 Ok(v)  => v,
 
                 Err(quiche::Error::Done) => {
@@ -233,11 +255,50 @@ fn main() {
                     break;
                 }
             }
+
+            // Drain any DATAGRAM frames that arrived alongside stream data,
+            // and echo each one straight back out so the send path (and the
+            // overflow behaviour of the bounded send queue) actually gets
+            // exercised, the way a media relay would bounce frames onward.
+            loop {
+                let len = match h3conn.quic_conn.dgram_recv(&mut dgram_buf) {
+                    Ok(v) => v,
+
+                    Err(quiche::Error::Done) => break,
+
+                    Err(e) => {
+                        error!("{} dgram recv failed: {:?}", h3conn.quic_conn.trace_id(), e);
+                        break;
+                    },
+                };
+
+                info!("{} received {} bytes of DATAGRAM data", h3conn.quic_conn.trace_id(), len);
+
+                if let Some(max_len) = h3conn.quic_conn.dgram_max_writable_len() {
+                    if len <= max_len {
+                        if let Err(e) = h3conn.quic_conn.dgram_send(&dgram_buf[..len]) {
+                            warn!("{} dgram send failed: {:?}", h3conn.quic_conn.trace_id(), e);
+                        }
+                    }
+                }
+            }
         }
 
-        for (peer, conn) in h3connections.values_mut() {
+        for conn in h3connections.values_mut() {
+            // Pick up any DoH upstream queries that finished on their
+            // worker thread since the last time around the loop.
+            conn.poll_doh();
+
+            // Pump streaming response bodies (e.g. a growing fragmented MP4)
+            // registered with handle_stream, now that the previous send()
+            // may have freed flow-control/congestion budget for them.
+            let writable: Vec<u64> = conn.quic_conn.writable().collect();
+            for s in writable {
+                conn.pump_stream(s);
+            }
+
             loop {
-                let write = match conn.quic_conn.send(&mut out) {
+                let (write, send_info) = match conn.quic_conn.send(&mut out) {
                     Ok(v) => v,
 
                     Err(quiche::Error::Done) => {
@@ -253,14 +314,14 @@ fn main() {
                 };
 
                 // TODO: coalesce packets.
-                socket.send_to(&out[..write], &peer).unwrap();
+                socket.send_to(&out[..write], &send_info.to).unwrap();
 
                 debug!("{} written {} bytes", conn.quic_conn.trace_id(), write);
             }
         }
 
         // Garbage collect closed connections.
-        h3connections.retain(|_, (_, ref mut c)| {
+        h3connections.retain(|_, c| {
             debug!("Collecting garbage");
 
             if c.quic_conn.is_closed() {
@@ -272,47 +333,6 @@ fn main() {
     }
 }
 
-fn mint_token(hdr: &quiche::Header, src: &net::SocketAddr) -> Vec<u8> {
-    let mut token = Vec::new();
-
-    token.extend_from_slice(b"quiche");
-
-    let addr = match src.ip() {
-        std::net::IpAddr::V4(a) => a.octets().to_vec(),
-        std::net::IpAddr::V6(a) => a.octets().to_vec(),
-    };
-
-    token.extend_from_slice(&addr);
-    token.extend_from_slice(&hdr.dcid);
-
-    token
-}
-
-fn validate_token<'a>(src: &net::SocketAddr, token: &'a [u8]) -> Option<&'a [u8]> {
-    if token.len() < 6 {
-        return None;
-    }
-
-    if &token[..6] != b"quiche" {
-        return None;
-    }
-
-    let token = &token[6..];
-
-    let addr = match src.ip() {
-        std::net::IpAddr::V4(a) => a.octets().to_vec(),
-        std::net::IpAddr::V6(a) => a.octets().to_vec(),
-    };
-
-    if token.len() < addr.len() || &token[..addr.len()] != addr.as_slice() {
-        return None;
-    }
-
-    let token = &token[addr.len()..];
-
-    Some(&token[..])
-}
-
 fn hex_dump(buf: &[u8]) -> String {
     let vec: Vec<String> = buf.iter()
                               .map(|b| format!("{:02x}", b))
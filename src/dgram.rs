@@ -0,0 +1,158 @@
+// Copyright (C) 2019, The quiche Authors.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright
+//       notice, this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Unreliable DATAGRAM frames, as per RFC 9221.
+//!
+//! DATAGRAM frames are not subject to flow control or retransmission: they
+//! are carried in a bounded FIFO queue in each direction, and the oldest
+//! queued datagram is dropped to make room when the queue is full.
+
+use std::collections::VecDeque;
+
+/// Frame type for a DATAGRAM frame whose payload runs to the end of the
+/// packet (no explicit length).
+pub const FRAME_TYPE_DATAGRAM: u8 = 0x30;
+
+/// Frame type for a DATAGRAM frame carrying an explicit length, allowing
+/// further frames to follow it in the same packet.
+pub const FRAME_TYPE_DATAGRAM_WITH_LEN: u8 = 0x31;
+
+#[derive(Debug)]
+pub struct DatagramQueue {
+    queue: VecDeque<Vec<u8>>,
+    max_len: usize,
+}
+
+impl DatagramQueue {
+    pub fn new(max_len: usize) -> DatagramQueue {
+        DatagramQueue { queue: VecDeque::with_capacity(max_len.min(16)), max_len }
+    }
+
+    /// Pushes `data` onto the back of the queue, dropping the oldest queued
+    /// datagram if the queue is already at capacity.
+    pub fn push(&mut self, data: Vec<u8>) {
+        if self.max_len == 0 {
+            return;
+        }
+
+        if self.queue.len() >= self.max_len {
+            self.queue.pop_front();
+        }
+
+        self.queue.push_back(data);
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.queue.pop_front()
+    }
+
+    pub fn peek_len(&self) -> Option<usize> {
+        self.queue.front().map(|d| d.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// Encodes `payload` as a DATAGRAM frame with an explicit length, appending
+/// it to `out`. Returns `None`, writing nothing, if `payload` is too long
+/// for the 2-byte length field.
+pub fn encode(payload: &[u8], out: &mut Vec<u8>) -> Option<()> {
+    if payload.len() > u16::MAX as usize {
+        return None;
+    }
+
+    out.push(FRAME_TYPE_DATAGRAM_WITH_LEN);
+    out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+
+    Some(())
+}
+
+/// Decodes a DATAGRAM frame starting at `buf[0]` (the frame type byte).
+///
+/// Returns the frame's payload and the number of bytes consumed from `buf`.
+/// A frame of type [`FRAME_TYPE_DATAGRAM`] consumes the rest of `buf`, since
+/// it carries no explicit length.
+pub fn decode(buf: &[u8]) -> Option<(&[u8], usize)> {
+    let ty = *buf.first()?;
+
+    match ty {
+        FRAME_TYPE_DATAGRAM => {
+            let payload = &buf[1..];
+            Some((payload, buf.len()))
+        },
+
+        FRAME_TYPE_DATAGRAM_WITH_LEN => {
+            let len_bytes = buf.get(1..3)?;
+            let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+
+            let payload = buf.get(3..3 + len)?;
+            Some((payload, 3 + len))
+        },
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_drops_oldest_on_overflow() {
+        let mut q = DatagramQueue::new(2);
+
+        q.push(b"a".to_vec());
+        q.push(b"b".to_vec());
+        q.push(b"c".to_vec());
+
+        assert_eq!(q.pop(), Some(b"b".to_vec()));
+        assert_eq!(q.pop(), Some(b"c".to_vec()));
+        assert_eq!(q.pop(), None);
+    }
+
+    #[test]
+    fn roundtrip_with_len() {
+        let mut out = Vec::new();
+        encode(b"hello", &mut out).unwrap();
+
+        let (payload, consumed) = decode(&out).unwrap();
+        assert_eq!(payload, b"hello");
+        assert_eq!(consumed, out.len());
+    }
+
+    #[test]
+    fn roundtrip_no_len_consumes_rest_of_packet() {
+        let mut buf = vec![FRAME_TYPE_DATAGRAM];
+        buf.extend_from_slice(b"world");
+
+        let (payload, consumed) = decode(&buf).unwrap();
+        assert_eq!(payload, b"world");
+        assert_eq!(consumed, buf.len());
+    }
+}
@@ -0,0 +1,645 @@
+// Copyright (C) 2019, The quiche Authors.
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+//     * Redistributions of source code must retain the above copyright
+//       notice, this list of conditions and the following disclaimer.
+//
+//     * Redistributions in binary form must reproduce the above copyright
+//       notice, this list of conditions and the following disclaimer in the
+//       documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS
+// IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO,
+// THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR
+// PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! A small HTTP-over-QUIC layer on top of [`crate::Connection`].
+//!
+//! Requests are carried one per bidirectional stream as a minimal
+//! HTTP/1.1-style request line and headers (`METHOD SP PATH\r\nheader:
+//! value\r\n\r\n<body>`), rather than real QPACK-encoded HEADERS frames --
+//! full QPACK is out of scope for this crate. `handle_stream` dispatches
+//! each request by path: `/dns-query` goes to the DNS-over-HTTP/3
+//! responder, which then reads the query from a POST body (content-type
+//! `application/dns-message`) or a GET `dns=` parameter; every other path
+//! goes to the streaming file responder.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::Connection;
+use crate::Error;
+use crate::Result;
+
+const DOH_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+const DOH_CONTENT_TYPE: &str = "application/dns-message";
+const FILE_CHUNK_LEN: usize = 8192;
+
+pub struct H3Config {
+    pub quiche_config: crate::Config,
+    root_dir: Option<PathBuf>,
+    doh_upstream: Option<SocketAddr>,
+}
+
+impl H3Config {
+    pub fn new(version: u32) -> Result<H3Config> {
+        Ok(H3Config { quiche_config: crate::Config::new(version)?, root_dir: None, doh_upstream: None })
+    }
+
+    pub fn set_root_dir(&mut self, dir: &str) {
+        self.root_dir = Some(PathBuf::from(dir));
+    }
+
+    /// Configures the upstream UDP resolver that DoH (RFC 8484) queries to
+    /// `/dns-query` are forwarded to.
+    pub fn set_doh_upstream(&mut self, upstream: SocketAddr) {
+        self.doh_upstream = Some(upstream);
+    }
+}
+
+/// A source of response body bytes, pumped in chunks bounded by the
+/// stream's current send capacity. `fin` is only reported once the source
+/// is actually exhausted, so a handler can start sending a response before
+/// all of its bytes exist yet (e.g. a file that's still being written to).
+pub trait ResponseSource {
+    /// Returns the next chunk of at most `max_len` bytes, and whether the
+    /// source is now exhausted.
+    fn next_chunk(&mut self, max_len: usize) -> Result<(Vec<u8>, bool)>;
+}
+
+struct InMemorySource {
+    data: Vec<u8>,
+    off: usize,
+}
+
+impl ResponseSource for InMemorySource {
+    fn next_chunk(&mut self, max_len: usize) -> Result<(Vec<u8>, bool)> {
+        let take = (self.data.len() - self.off).min(max_len);
+        let chunk = self.data[self.off..self.off + take].to_vec();
+        self.off += take;
+
+        Ok((chunk, self.off == self.data.len()))
+    }
+}
+
+/// Pumps a file in chunks, reading only as much as currently exists on
+/// disk. A file that's still growing (e.g. a fragment-per-frame MP4 mux)
+/// is delivered incrementally rather than read in full up front.
+struct FileChunkSource {
+    file: File,
+    path: PathBuf,
+    last_len: u64,
+}
+
+impl ResponseSource for FileChunkSource {
+    fn next_chunk(&mut self, max_len: usize) -> Result<(Vec<u8>, bool)> {
+        let mut buf = vec![0; max_len.min(FILE_CHUNK_LEN)];
+        let n = self.file.read(&mut buf).map_err(|_| Error::Done)?;
+        buf.truncate(n);
+
+        if n > 0 {
+            return Ok((buf, false));
+        }
+
+        // A 0-byte read only means "nothing new since our last read", not
+        // "the file is complete": a writer may still be appending to it.
+        // The only way to tell those apart without an out-of-band
+        // completion signal is to compare the file's size on disk across
+        // calls -- if it hasn't grown since we last checked, treat it as
+        // done; otherwise keep the source registered and try again next
+        // time the stream is writable.
+        let current_len = std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(self.last_len);
+        let done = current_len == self.last_len;
+        self.last_len = current_len;
+
+        Ok((buf, done))
+    }
+}
+
+#[derive(Default)]
+struct RequestState {
+    buf: Vec<u8>,
+    fin_seen: bool,
+    dispatched: bool,
+}
+
+pub struct H3Connection {
+    pub quic_conn: Connection,
+    established: bool,
+    root_dir: Option<PathBuf>,
+    doh_upstream: Option<SocketAddr>,
+    requests: HashMap<u64, RequestState>,
+    sources: HashMap<u64, Box<dyn ResponseSource + Send>>,
+    doh_pending: HashMap<u64, mpsc::Receiver<std::io::Result<Vec<u8>>>>,
+}
+
+impl H3Connection {
+    pub fn is_established(&self) -> bool {
+        self.established
+    }
+
+    pub fn send_settings(&mut self) {
+        self.established = true;
+    }
+
+    pub fn open_qpack_streams(&mut self) {
+        // No-op: this crate doesn't implement QPACK.
+    }
+
+    /// Reads what's available on `stream_id`, and once the full request
+    /// (headers, plus body for methods that carry one) has arrived,
+    /// dispatches it to the DoH responder or the static file responder.
+    pub fn handle_stream(&mut self, stream_id: u64) -> Result<()> {
+        let state = self.requests.entry(stream_id).or_default();
+
+        if state.dispatched {
+            return Ok(());
+        }
+
+        let mut chunk = [0; 4096];
+        loop {
+            match self.quic_conn.stream_recv(stream_id, &mut chunk) {
+                Ok((len, fin)) => {
+                    let state = self.requests.get_mut(&stream_id).unwrap();
+                    state.buf.extend_from_slice(&chunk[..len]);
+                    state.fin_seen |= fin;
+
+                    if fin {
+                        break;
+                    }
+                },
+
+                Err(Error::Done) => break,
+
+                Err(e) => return Err(e),
+            }
+        }
+
+        let state = self.requests.get(&stream_id).unwrap();
+        let request = match parse_request(&state.buf) {
+            Some(r) => r,
+            None => return Ok(()), // headers not fully in yet
+        };
+
+        // POST/PUT bodies must be fully buffered before dispatch; GET has
+        // none, so it can be served as soon as the request line is parsed.
+        if request.method != "GET" && !state.fin_seen {
+            return Ok(());
+        }
+
+        self.requests.get_mut(&stream_id).unwrap().dispatched = true;
+
+        if is_doh_path(&request.path) {
+            self.handle_doh(stream_id, &request)
+        } else {
+            self.handle_file(stream_id, &request)
+        }
+    }
+
+    /// Kicks off the upstream DNS query on a worker thread and returns
+    /// immediately -- `forward_to_upstream` blocks on a UDP round trip for
+    /// up to `DOH_UPSTREAM_TIMEOUT`, and this is called from the same
+    /// single-threaded loop that drives every other connection, so it must
+    /// never block here. The response is picked up later by [`poll_doh`].
+    ///
+    /// [`poll_doh`]: Self::poll_doh
+    fn handle_doh(&mut self, stream_id: u64, req: &ParsedRequest) -> Result<()> {
+        let query = match doh_query_bytes(req) {
+            Some(q) => q,
+
+            None => {
+                return self.respond_once(stream_id, "400", "text/plain", b"missing DNS query".to_vec());
+            },
+        };
+
+        let upstream = match self.doh_upstream {
+            Some(u) => u,
+
+            None => {
+                return self.respond_once(stream_id, "503", "text/plain", b"DoH upstream not configured".to_vec());
+            },
+        };
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(forward_to_upstream(upstream, &query));
+        });
+
+        self.doh_pending.insert(stream_id, rx);
+        Ok(())
+    }
+
+    /// Picks up any DoH worker threads kicked off by [`handle_doh`] that
+    /// have since finished, and dispatches their response. Must be called
+    /// regularly (e.g. once per event loop iteration) for a DoH response to
+    /// ever actually be sent; never blocks.
+    ///
+    /// [`handle_doh`]: Self::handle_doh
+    pub fn poll_doh(&mut self) {
+        let mut done = Vec::new();
+
+        self.doh_pending.retain(|&stream_id, rx| {
+            match rx.try_recv() {
+                Ok(result) => {
+                    done.push((stream_id, result));
+                    false
+                },
+
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    done.push((stream_id, Err(std::io::Error::from(std::io::ErrorKind::Other))));
+                    false
+                },
+
+                Err(mpsc::TryRecvError::Empty) => true,
+            }
+        });
+
+        for (stream_id, result) in done {
+            let _ = match result {
+                Ok(answer) => self.deliver_doh_answer(stream_id, answer),
+                Err(_) => self.respond_once(stream_id, "502", "text/plain", b"upstream resolver failed".to_vec()),
+            };
+        }
+    }
+
+    fn deliver_doh_answer(&mut self, stream_id: u64, answer: Vec<u8>) -> Result<()> {
+        let ttl = min_answer_ttl(&answer).unwrap_or(0);
+
+        self.send_response_headers(
+            stream_id,
+            "200",
+            &[("content-type", DOH_CONTENT_TYPE), ("cache-control", &format!("max-age={}", ttl))],
+        )?;
+
+        self.sources.insert(stream_id, Box::new(InMemorySource { data: answer, off: 0 }));
+        Ok(())
+    }
+
+    fn handle_file(&mut self, stream_id: u64, req: &ParsedRequest) -> Result<()> {
+        let root = match &self.root_dir {
+            Some(r) => r.clone(),
+            None => return self.respond_once(stream_id, "500", "text/plain", b"no root directory configured".to_vec()),
+        };
+
+        let path = match sanitize_path(&req.path) {
+            Some(p) => p,
+            None => return self.respond_once(stream_id, "400", "text/plain", b"invalid path".to_vec()),
+        };
+
+        let full_path = root.join(path);
+
+        let file = match File::open(&full_path) {
+            Ok(f) => f,
+            Err(_) => return self.respond_once(stream_id, "404", "text/plain", b"Not Found".to_vec()),
+        };
+
+        let last_len = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        self.send_response_headers(stream_id, "200", &[("content-type", guess_mime(&full_path))])?;
+        self.sources.insert(stream_id, Box::new(FileChunkSource { file, path: full_path, last_len }));
+        Ok(())
+    }
+
+    fn respond_once(&mut self, stream_id: u64, status: &str, content_type: &str, body: Vec<u8>) -> Result<()> {
+        self.send_response_headers(stream_id, status, &[("content-type", content_type)])?;
+        self.sources.insert(stream_id, Box::new(InMemorySource { data: body, off: 0 }));
+        Ok(())
+    }
+
+    /// Sends the response's header line immediately, ahead of any body
+    /// bytes, which may not exist yet if the body comes from a streaming
+    /// [`ResponseSource`].
+    fn send_response_headers(&mut self, stream_id: u64, status: &str, headers: &[(&str, &str)]) -> Result<()> {
+        let mut out = format!("HTTP/3 {}\r\n", status);
+        for (k, v) in headers {
+            out.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        out.push_str("\r\n");
+
+        self.quic_conn.stream_send(stream_id, out.as_bytes(), false)?;
+        Ok(())
+    }
+
+    /// Pumps the response source registered for `stream_id`, if any,
+    /// emitting a DATA chunk bounded by the stream's current send
+    /// capacity and setting `fin` only once the source is exhausted.
+    pub fn pump_stream(&mut self, stream_id: u64) {
+        let cap = match self.quic_conn.stream_capacity(stream_id) {
+            Ok(c) if c > 0 => c,
+            _ => return,
+        };
+
+        let done = {
+            let source = match self.sources.get_mut(&stream_id) {
+                Some(s) => s,
+                None => return,
+            };
+
+            let (chunk, eof) = match source.next_chunk(cap) {
+                Ok(v) => v,
+                Err(_) => return,
+            };
+
+            if !chunk.is_empty() || eof {
+                let _ = self.quic_conn.stream_send(stream_id, &chunk, eof);
+            }
+
+            eof
+        };
+
+        if done {
+            self.sources.remove(&stream_id);
+        }
+    }
+}
+
+struct ParsedRequest {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Parses `METHOD SP PATH\r\nheader: value\r\n...\r\n\r\n<body>` out of
+/// `buf`, returning `None` until the header block has fully arrived.
+fn parse_request(buf: &[u8]) -> Option<ParsedRequest> {
+    let header_end = find_subslice(buf, b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&buf[..header_end]).ok()?;
+    let body = buf[header_end + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next()?;
+    let mut parts = request_line.split(' ');
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    for line in lines {
+        if let Some(idx) = line.find(':') {
+            let key = line[..idx].trim().to_ascii_lowercase();
+            let value = line[idx + 1..].trim().to_string();
+            headers.insert(key, value);
+        }
+    }
+
+    Some(ParsedRequest { method, path, headers, body })
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Whether `path` (as taken straight from the request line, query string and
+/// all) names the DoH endpoint. Matches only `/dns-query` itself, not e.g.
+/// `/dns-query-export.html`, which should fall through to the static file
+/// responder instead.
+fn is_doh_path(path: &str) -> bool {
+    path.split_once('?').map_or(path, |(p, _)| p) == "/dns-query"
+}
+
+fn doh_query_bytes(req: &ParsedRequest) -> Option<Vec<u8>> {
+    if req.method == "POST" {
+        if req.headers.get("content-type").map(String::as_str) != Some(DOH_CONTENT_TYPE) {
+            return None;
+        }
+
+        return Some(req.body.clone());
+    }
+
+    if req.method == "GET" {
+        let query = req.path.split_once('?')?.1;
+        let dns_param = query.split('&').find_map(|kv| {
+            let mut it = kv.splitn(2, '=');
+            if it.next()? == "dns" {
+                it.next()
+            } else {
+                None
+            }
+        })?;
+
+        return base64::decode_config(dns_param, base64::URL_SAFE_NO_PAD).ok();
+    }
+
+    None
+}
+
+fn forward_to_upstream(upstream: SocketAddr, query: &[u8]) -> std::io::Result<Vec<u8>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(DOH_UPSTREAM_TIMEOUT))?;
+    socket.send_to(query, upstream)?;
+
+    let mut buf = [0; 4096];
+    let len = socket.recv(&mut buf)?;
+
+    Ok(buf[..len].to_vec())
+}
+
+/// Walks a DNS response message's answer section to find the minimum TTL
+/// among its resource records, for use as the DoH response's
+/// `cache-control: max-age`.
+fn min_answer_ttl(msg: &[u8]) -> Option<u32> {
+    if msg.len() < 12 {
+        return None;
+    }
+
+    let qdcount = u16::from_be_bytes([msg[4], msg[5]]) as usize;
+    let ancount = u16::from_be_bytes([msg[6], msg[7]]) as usize;
+
+    let mut off = 12;
+    for _ in 0..qdcount {
+        off = skip_dns_name(msg, off)?;
+        off += 4; // QTYPE + QCLASS
+    }
+
+    let mut min_ttl = None;
+    for _ in 0..ancount {
+        off = skip_dns_name(msg, off)?;
+
+        let ttl = u32::from_be_bytes(msg.get(off + 4..off + 8)?.try_into().ok()?);
+        let rdlength = u16::from_be_bytes(msg.get(off + 8..off + 10)?.try_into().ok()?) as usize;
+        off += 10 + rdlength;
+
+        min_ttl = Some(min_ttl.map_or(ttl, |m: u32| m.min(ttl)));
+    }
+
+    min_ttl
+}
+
+fn skip_dns_name(buf: &[u8], mut off: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(off)? as usize;
+
+        if len == 0 {
+            return Some(off + 1);
+        }
+
+        // Compression pointer: the two top bits of the length byte are set.
+        if len & 0xc0 == 0xc0 {
+            return Some(off + 2);
+        }
+
+        off += 1 + len;
+    }
+}
+
+fn sanitize_path(path: &str) -> Option<PathBuf> {
+    let path = path.split('?').next().unwrap();
+    let path = path.trim_start_matches('/');
+    let path = if path.is_empty() { "index.html" } else { path };
+
+    if path.split('/').any(|c| c == "..") {
+        return None;
+    }
+
+    Some(PathBuf::from(path))
+}
+
+fn guess_mime(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") | Some("htm") => "text/html",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("css") => "text/css",
+        Some("mp4") => "video/mp4",
+        Some("txt") => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Completes the (synchronous, in this crate) handshake and returns a new
+/// server-side HTTP/3 connection.
+pub fn accept(scid: &[u8], odcid: Option<&[u8]>, local_addr: SocketAddr, peer_addr: SocketAddr, peer_cid: &[u8], config: &mut H3Config) -> Result<Box<H3Connection>> {
+    let quic_conn = crate::accept(scid, odcid, local_addr, peer_addr, peer_cid, &mut config.quiche_config)?;
+
+    Ok(Box::new(H3Connection {
+        quic_conn,
+        established: false,
+        root_dir: config.root_dir.clone(),
+        doh_upstream: config.doh_upstream,
+        requests: HashMap::new(),
+        sources: HashMap::new(),
+        doh_pending: HashMap::new(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_get_request_line() {
+        let req = parse_request(b"GET /index.html HTTP/1.1\r\nhost: example\r\n\r\n").unwrap();
+        assert_eq!(req.method, "GET");
+        assert_eq!(req.path, "/index.html");
+    }
+
+    #[test]
+    fn waits_for_full_headers() {
+        assert!(parse_request(b"GET /index.html HTTP/1.1\r\nhost: exa").is_none());
+    }
+
+    #[test]
+    fn doh_get_param_is_base64url_decoded() {
+        let query = b"\x00\x01\x02\x03";
+        let encoded = base64::encode_config(query, base64::URL_SAFE_NO_PAD);
+        let path = format!("/dns-query?dns={}", encoded);
+
+        let req = ParsedRequest { method: "GET".to_string(), path, headers: HashMap::new(), body: Vec::new() };
+
+        assert_eq!(doh_query_bytes(&req).unwrap(), query);
+    }
+
+    #[test]
+    fn doh_post_requires_dns_message_content_type() {
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "application/dns-message".to_string());
+
+        let req = ParsedRequest { method: "POST".to_string(), path: "/dns-query".to_string(), headers, body: b"query".to_vec() };
+        assert_eq!(doh_query_bytes(&req).unwrap(), b"query");
+
+        let req_missing_ct = ParsedRequest { method: "POST".to_string(), path: "/dns-query".to_string(), headers: HashMap::new(), body: b"query".to_vec() };
+        assert!(doh_query_bytes(&req_missing_ct).is_none());
+    }
+
+    #[test]
+    fn is_doh_path_matches_exactly() {
+        assert!(is_doh_path("/dns-query"));
+        assert!(is_doh_path("/dns-query?dns=abc"));
+        assert!(!is_doh_path("/dns-query-export.html"));
+        assert!(!is_doh_path("/dns-query/other"));
+    }
+
+    #[test]
+    fn sanitize_path_rejects_traversal() {
+        assert!(sanitize_path("/../../etc/passwd").is_none());
+        assert_eq!(sanitize_path("/foo/bar.html").unwrap(), PathBuf::from("foo/bar.html"));
+        assert_eq!(sanitize_path("/").unwrap(), PathBuf::from("index.html"));
+    }
+
+    #[test]
+    fn file_chunk_source_waits_for_a_still_growing_file() {
+        let path = std::env::temp_dir().join(format!("quiche-test-{:?}.bin", std::thread::current().id()));
+        std::fs::write(&path, b"hello").unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut source = FileChunkSource { file, path: path.clone(), last_len: 5 };
+
+        // All 5 bytes currently on disk are read; a read that returns data
+        // never reports eof, since the writer may still append more.
+        let (chunk, eof) = source.next_chunk(4096).unwrap();
+        assert_eq!(chunk, b"hello");
+        assert!(!eof);
+
+        // A read that catches up to the writer returns 0 bytes; since the
+        // size on disk hasn't changed since we last checked, this is a
+        // real EOF.
+        let (chunk, eof) = source.next_chunk(4096).unwrap();
+        assert!(chunk.is_empty());
+        assert!(eof);
+
+        // Simulate a writer appending more data between polls.
+        std::fs::write(&path, b"hello world").unwrap();
+        let (chunk, eof) = source.next_chunk(4096).unwrap();
+        assert_eq!(chunk, b" world");
+        assert!(!eof);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn min_ttl_walks_answer_section() {
+        // Header (12 bytes): ID, flags, QDCOUNT=1, ANCOUNT=2, NSCOUNT=0, ARCOUNT=0.
+        let mut msg = vec![0, 0, 0, 0, 0, 1, 0, 2, 0, 0, 0, 0];
+        // Question: root name, QTYPE=1, QCLASS=1.
+        msg.extend_from_slice(&[0, 0, 1, 0, 1]);
+        // Answer 1: root name, TYPE=1, CLASS=1, TTL=300, RDLENGTH=0.
+        msg.extend_from_slice(&[0, 0, 1, 0, 1]);
+        msg.extend_from_slice(&300u32.to_be_bytes());
+        msg.extend_from_slice(&0u16.to_be_bytes());
+        // Answer 2: root name, TYPE=1, CLASS=1, TTL=60, RDLENGTH=0.
+        msg.extend_from_slice(&[0, 0, 1, 0, 1]);
+        msg.extend_from_slice(&60u32.to_be_bytes());
+        msg.extend_from_slice(&0u16.to_be_bytes());
+
+        assert_eq!(min_answer_ttl(&msg), Some(60));
+    }
+}